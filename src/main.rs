@@ -1,9 +1,344 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use clap::Parser;
-use cosmos_sdk_proto::{cosmos, traits::MessageExt};
-use futures::future;
+use cosmos_sdk_proto::{cosmos, prost, traits::MessageExt, Any};
+use futures::stream::{self, TryStreamExt};
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tonic::transport::{Channel, Endpoint};
+
+/// Account type used by Ethermint-based chains (e.g. EVMOS-style hubs), which wrap a
+/// standard Cosmos-SDK `BaseAccount` together with an EVM code hash.
+///
+/// `cosmos_sdk_proto` doesn't vendor Ethermint's protos, so we declare just enough of
+/// `/ethermint.types.v1.EthAccount` to pull out the fields we need.
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct EthAccount {
+    #[prost(message, optional, tag = "1")]
+    base_account: Option<cosmos::auth::v1beta1::BaseAccount>,
+    #[prost(bytes = "vec", tag = "2")]
+    code_hash: Vec<u8>,
+}
+
+const ETHERMINT_ACCOUNT_TYPE_URL: &str = "/ethermint.types.v1.EthAccount";
+
+/// Decodes an auth query's `account` field, transparently handling both plain
+/// Cosmos-SDK `BaseAccount`s and Ethermint's `EthAccount` wrapper. Returns `None` if the
+/// inner `base_account` is absent (only possible for the `EthAccount` variant).
+fn decode_base_account(any: &Any) -> Result<Option<cosmos::auth::v1beta1::BaseAccount>, Error> {
+    if any.type_url == ETHERMINT_ACCOUNT_TYPE_URL {
+        let eth_account: EthAccount = prost::Message::decode(any.value.as_slice())?;
+        Ok(eth_account.base_account)
+    } else {
+        Ok(Some(<cosmos::auth::v1beta1::BaseAccount>::from_any(any)?))
+    }
+}
+
+/// The auth query's JSON response, as served by the LCD/REST gateway. Mirrors
+/// [`decode_base_account`]'s polymorphism over plain `BaseAccount`s and Ethermint's
+/// `EthAccount` wrapper, keyed off the `@type` field the gateway tags responses with.
+#[derive(Deserialize)]
+struct RestAccountResponse {
+    account: RestAccount,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "@type")]
+enum RestAccount {
+    #[serde(rename = "/ethermint.types.v1.EthAccount")]
+    Eth { base_account: RestBaseAccount },
+    #[serde(rename = "/cosmos.auth.v1beta1.BaseAccount")]
+    Base(RestBaseAccount),
+}
+
+#[derive(Deserialize)]
+struct RestBaseAccount {
+    sequence: String,
+}
+
+#[derive(Deserialize)]
+struct RestDelegationsResponse {
+    delegation_responses: Vec<RestDelegationResponse>,
+    pagination: Option<RestPageResponse>,
+}
+
+#[derive(Deserialize)]
+struct RestDelegationResponse {
+    balance: Option<RestCoin>,
+}
+
+#[derive(Deserialize)]
+struct RestCoin {
+    amount: String,
+}
+
+#[derive(Deserialize)]
+struct RestPageResponse {
+    next_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RestBalanceResponse {
+    balance: Option<RestCoin>,
+}
+
+#[derive(Deserialize)]
+struct RestUnbondingResponse {
+    unbonding_responses: Vec<RestUnbondingDelegation>,
+}
+
+#[derive(Deserialize)]
+struct RestUnbondingDelegation {
+    entries: Vec<RestUnbondingEntry>,
+}
+
+#[derive(Deserialize)]
+struct RestUnbondingEntry {
+    balance: String,
+}
+
+#[derive(Deserialize)]
+struct RestRewardsResponse {
+    total: Vec<RestDecCoin>,
+}
+
+#[derive(Deserialize)]
+struct RestDecCoin {
+    denom: String,
+    amount: String,
+}
+
+/// Denom of the token being airdropped; the one we sum balances, delegations, and
+/// rewards in.
+const MARS_DENOM: &str = "umars";
+
+/// Number of decimal places in a Cosmos-SDK `LegacyDec`.
+const DEC_PRECISION: u32 = 18;
+
+/// Truncates a raw gRPC `DecCoin` amount down to its integer part. Over protobuf, a
+/// `LegacyDec` marshals as its internal big-int string scaled by `10^18` with no
+/// decimal point (e.g. `"1500000000000000000"` for 1.5), unlike the dotted decimal
+/// string the LCD/JSON gateway renders.
+fn dec_coin_floor_grpc(amount: &str) -> Result<u128, Error> {
+    let scaled: u128 = amount.parse()?;
+    Ok(scaled / 10u128.pow(DEC_PRECISION))
+}
+
+/// Truncates an LCD/JSON `DecCoin` amount (a dotted decimal string such as
+/// `"123000000000000000000.000000000000000000"`) down to its integer part.
+fn dec_coin_floor_rest(amount: &str) -> Result<u128, Error> {
+    Ok(amount.split('.').next().unwrap_or(amount).parse()?)
+}
+
+/// The transport used to query account sequences and staked, liquid, unbonding, and
+/// reward amounts. Cloning is cheap:
+/// a gRPC `Channel` is a handle to a shared connection, and `reqwest::Client` is
+/// internally reference-counted.
+#[derive(Clone)]
+enum Transport {
+    Grpc(Channel),
+    Rest { client: reqwest::Client, base_url: String },
+}
+
+async fn query_account_grpc(channel: Channel, address: &str) -> Result<u64, Error> {
+    let sequence = cosmos::auth::v1beta1::query_client::QueryClient::new(channel)
+        .account(cosmos::auth::v1beta1::QueryAccountRequest {
+            address: address.to_string(),
+        })
+        .await?
+        .into_inner()
+        .account
+        .as_ref()
+        .map(decode_base_account)
+        .transpose()?
+        .flatten()
+        .ok_or_else(|| Error::AccountNotFound {
+            address: address.to_string(),
+        })?
+        .sequence;
+
+    Ok(sequence)
+}
+
+async fn query_staked_grpc(channel: Channel, address: &str) -> Result<u128, Error> {
+    let mut staking_qc = cosmos::staking::v1beta1::query_client::QueryClient::new(channel);
+
+    let mut staked_amount = 0u128;
+    let mut page_key = Vec::new();
+    loop {
+        let response = staking_qc
+            .delegator_delegations(cosmos::staking::v1beta1::QueryDelegatorDelegationsRequest {
+                delegator_addr: address.to_string(),
+                pagination: Some(cosmos::base::query::v1beta1::PageRequest {
+                    key: page_key,
+                    ..Default::default()
+                }),
+            })
+            .await?
+            .into_inner();
+
+        staked_amount = response.delegation_responses.into_iter().try_fold(
+            staked_amount,
+            |mut total, del| -> Result<_, Error> {
+                if let Some(coin) = del.balance {
+                    total += coin.amount.parse::<u128>()?;
+                }
+                Ok(total)
+            },
+        )?;
+
+        page_key = response.pagination.map(|p| p.next_key).unwrap_or_default();
+        if page_key.is_empty() {
+            break;
+        }
+    }
+
+    Ok(staked_amount)
+}
+
+async fn query_account_rest(client: &reqwest::Client, base_url: &str, address: &str) -> Result<u64, Error> {
+    let url = format!("{base_url}/cosmos/auth/v1beta1/accounts/{address}");
+    let response = client.get(url).send().await?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(Error::AccountNotFound {
+            address: address.to_string(),
+        });
+    }
+
+    let body: RestAccountResponse = response.error_for_status()?.json().await?;
+    let sequence = match body.account {
+        RestAccount::Eth { base_account } => base_account.sequence,
+        RestAccount::Base(base_account) => base_account.sequence,
+    };
+
+    Ok(sequence.parse()?)
+}
+
+async fn query_staked_rest(client: &reqwest::Client, base_url: &str, address: &str) -> Result<u128, Error> {
+    let url = format!("{base_url}/cosmos/staking/v1beta1/delegations/{address}");
+
+    let mut staked_amount = 0u128;
+    let mut page_key: Option<String> = None;
+    loop {
+        let mut request = client.get(&url);
+        if let Some(key) = &page_key {
+            request = request.query(&[("pagination.key", key)]);
+        }
+
+        let body: RestDelegationsResponse = request.send().await?.error_for_status()?.json().await?;
+
+        staked_amount = body.delegation_responses.into_iter().try_fold(
+            staked_amount,
+            |mut total, del| -> Result<_, Error> {
+                if let Some(coin) = del.balance {
+                    total += coin.amount.parse::<u128>()?;
+                }
+                Ok(total)
+            },
+        )?;
+
+        page_key = body.pagination.and_then(|p| p.next_key).filter(|key| !key.is_empty());
+        if page_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(staked_amount)
+}
+
+async fn query_liquid_grpc(channel: Channel, address: &str) -> Result<u128, Error> {
+    let balance = cosmos::bank::v1beta1::query_client::QueryClient::new(channel)
+        .balance(cosmos::bank::v1beta1::QueryBalanceRequest {
+            address: address.to_string(),
+            denom: MARS_DENOM.to_string(),
+        })
+        .await?
+        .into_inner()
+        .balance;
+
+    match balance {
+        Some(coin) => Ok(coin.amount.parse()?),
+        None => Ok(0),
+    }
+}
+
+async fn query_unbonding_grpc(channel: Channel, address: &str) -> Result<u128, Error> {
+    cosmos::staking::v1beta1::query_client::QueryClient::new(channel)
+        .delegator_unbonding_delegations(cosmos::staking::v1beta1::QueryDelegatorUnbondingDelegationsRequest {
+            delegator_addr: address.to_string(),
+            pagination: None,
+        })
+        .await?
+        .into_inner()
+        .unbonding_responses
+        .into_iter()
+        .flat_map(|ubd| ubd.entries)
+        .try_fold(0u128, |mut total, entry| -> Result<_, Error> {
+            total += entry.balance.parse::<u128>()?;
+            Ok(total)
+        })
+}
+
+async fn query_rewards_grpc(channel: Channel, address: &str) -> Result<u128, Error> {
+    let total = cosmos::distribution::v1beta1::query_client::QueryClient::new(channel)
+        .delegation_total_rewards(cosmos::distribution::v1beta1::QueryDelegationTotalRewardsRequest {
+            delegator_address: address.to_string(),
+        })
+        .await?
+        .into_inner()
+        .total;
+
+    match total.into_iter().find(|coin| coin.denom == MARS_DENOM) {
+        Some(coin) => dec_coin_floor_grpc(&coin.amount),
+        None => Ok(0),
+    }
+}
+
+async fn query_liquid_rest(client: &reqwest::Client, base_url: &str, address: &str) -> Result<u128, Error> {
+    let url = format!("{base_url}/cosmos/bank/v1beta1/balances/{address}/by_denom");
+    let body: RestBalanceResponse = client
+        .get(url)
+        .query(&[("denom", MARS_DENOM)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    match body.balance {
+        Some(coin) => Ok(coin.amount.parse()?),
+        None => Ok(0),
+    }
+}
+
+async fn query_unbonding_rest(client: &reqwest::Client, base_url: &str, address: &str) -> Result<u128, Error> {
+    let url = format!("{base_url}/cosmos/staking/v1beta1/delegators/{address}/unbonding_delegations");
+    let body: RestUnbondingResponse = client.get(url).send().await?.error_for_status()?.json().await?;
+
+    body.unbonding_responses
+        .into_iter()
+        .flat_map(|ubd| ubd.entries)
+        .try_fold(0u128, |mut total, entry| -> Result<_, Error> {
+            total += entry.balance.parse::<u128>()?;
+            Ok(total)
+        })
+}
+
+async fn query_rewards_rest(client: &reqwest::Client, base_url: &str, address: &str) -> Result<u128, Error> {
+    let url = format!("{base_url}/cosmos/distribution/v1beta1/delegators/{address}/rewards");
+    let body: RestRewardsResponse = client.get(url).send().await?.error_for_status()?.json().await?;
+
+    match body.total.into_iter().find(|coin| coin.denom == MARS_DENOM) {
+        Some(coin) => dec_coin_floor_rest(&coin.amount),
+        None => Ok(0),
+    }
+}
 
 #[derive(Deserialize)]
 struct Input {
@@ -23,6 +358,46 @@ struct Output {
 
     /// How many MARS tokens the account is currently staking
     staked_amount: u128,
+
+    /// How many MARS tokens are sitting in the account's liquid (spendable) balance
+    liquid_amount: u128,
+
+    /// How many MARS tokens the account has undelegated but not yet withdrawn
+    unbonding_amount: u128,
+
+    /// How many MARS tokens the account has earned in staking rewards but not yet claimed
+    rewards_amount: u128,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum OutputFormat {
+    /// One JSON object per line, so a crash mid-run doesn't cost an unparseable file
+    Json,
+    Csv,
+}
+
+/// Appends a single `Output` record to the shared output file in the given format.
+async fn write_output(
+    writer: &Mutex<BufWriter<fs::File>>,
+    format: OutputFormat,
+    output: &Output,
+) -> Result<(), Error> {
+    let mut writer = writer.lock().await;
+    match format {
+        OutputFormat::Json => writeln!(writer, "{}", serde_json::to_string(output)?)?,
+        OutputFormat::Csv => writeln!(
+            writer,
+            "{},{},{},{},{},{},{}",
+            output.address,
+            output.sequence,
+            output.airdrop_amount,
+            output.staked_amount,
+            output.liquid_amount,
+            output.unbonding_amount,
+            output.rewards_amount,
+        )?,
+    }
+    Ok(())
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -48,6 +423,9 @@ enum Error {
     #[error(transparent)]
     Bech32(#[from] bech32::Error),
 
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+
     #[error("Account not found: {address}")]
     AccountNotFound {
         address: String,
@@ -65,8 +443,20 @@ pub struct Cli {
     pub output: Option<PathBuf>,
 
     /// URL to a gRPC endpoint
-    #[arg(long)]
-    pub grpc_url: String,
+    #[arg(long, conflicts_with = "rest_url", required_unless_present = "rest_url")]
+    pub grpc_url: Option<String>,
+
+    /// URL to a REST/LCD endpoint, as an alternative to --grpc-url
+    #[arg(long, conflicts_with = "grpc_url", required_unless_present = "grpc_url")]
+    pub rest_url: Option<String>,
+
+    /// Maximum number of accounts to query concurrently
+    #[arg(long, default_value_t = 16)]
+    pub concurrency: usize,
+
+    /// Output file format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
 }
 
 #[tokio::main]
@@ -75,73 +465,95 @@ async fn main() -> Result<(), Error> {
         input,
         output,
         grpc_url,
+        rest_url,
+        concurrency,
+        format,
     } = Cli::parse();
 
     let input_path = input.unwrap_or(PathBuf::from("./data/airdrop.json"));
     let output_path = output.unwrap_or(PathBuf::from("./data/output.json"));
 
     let input_str = fs::read_to_string(&input_path)?;
-    let mut users: Vec<Input> = serde_json::from_str(&input_str)?;
-    users.truncate(5);
+    let users: Vec<Input> = serde_json::from_str(&input_str)?;
 
-    let output = future::try_join_all(users.into_iter().map(|user| {
-        // https://stackoverflow.com/questions/66429545/clone-a-string-for-an-async-move-closure-in-rust
-        let grpc_url = grpc_url.clone();
+    let transport = match (grpc_url, rest_url) {
+        (Some(grpc_url), None) => Transport::Grpc(Endpoint::new(grpc_url)?.connect().await?),
+        (None, Some(rest_url)) => Transport::Rest {
+            client: reqwest::Client::new(),
+            base_url: rest_url,
+        },
+        _ => unreachable!("clap enforces exactly one of --grpc-url/--rest-url"),
+    };
+
+    let writer = Arc::new(Mutex::new(BufWriter::new(fs::File::create(&output_path)?)));
+    if let OutputFormat::Csv = format {
+        let mut writer = writer.lock().await;
+        // Columns mirror `Output` in full (including the liquid/unbonding/rewards fields
+        // added after this format was first written), not just address/sequence/airdrop/staked.
+        writeln!(
+            writer,
+            "address,sequence,airdrop_amount,staked_amount,liquid_amount,unbonding_amount,rewards_amount"
+        )?;
+    }
+
+    stream::iter(users.into_iter().map(Result::<_, Error>::Ok)).try_for_each_concurrent(concurrency, |user| {
+        let transport = transport.clone();
+        let writer = Arc::clone(&writer);
         async move {
             let (_, bytes, variant) = bech32::decode(&user.address)?;
             let address = bech32::encode("mars", bytes, variant)?;
 
-            let sequence = cosmos::auth::v1beta1::query_client::QueryClient::connect(grpc_url.clone())
-                .await?
-                .account(cosmos::auth::v1beta1::QueryAccountRequest {
-                    address: address.clone(),
-                })
-                .await?
-                .into_inner()
-                .account
-                .as_ref()
-                .map(<cosmos::auth::v1beta1::BaseAccount>::from_any)
-                .transpose()?
-                .ok_or_else(|| Error::AccountNotFound {
-                    address: address.clone(),
-                })?
-                .sequence;
-
-            let staked_amount = cosmos::staking::v1beta1::query_client::QueryClient::connect(grpc_url)
-                .await?
-                .delegator_delegations(cosmos::staking::v1beta1::QueryDelegatorDelegationsRequest {
-                    delegator_addr: address.clone(),
-                    pagination: None,
-                })
-                .await?
-                .into_inner()
-                .delegation_responses
-                .into_iter()
-                .try_fold(0u128, |mut total, del| -> Result<_, Error> {
-                    if let Some(coin) = del.balance {
-                        total += coin.amount.parse::<u128>()?;
-                    }
-                    Ok(total)
-                })?;
+            let (sequence, staked_amount, liquid_amount, unbonding_amount, rewards_amount) = match &transport {
+                Transport::Grpc(channel) => {
+                    let sequence = query_account_grpc(channel.clone(), &address).await?;
+                    let staked_amount = query_staked_grpc(channel.clone(), &address).await?;
+                    let liquid_amount = query_liquid_grpc(channel.clone(), &address).await?;
+                    let unbonding_amount = query_unbonding_grpc(channel.clone(), &address).await?;
+                    let rewards_amount = query_rewards_grpc(channel.clone(), &address).await?;
+                    (sequence, staked_amount, liquid_amount, unbonding_amount, rewards_amount)
+                }
+                Transport::Rest { client, base_url } => {
+                    let sequence = query_account_rest(client, base_url, &address).await?;
+                    let staked_amount = query_staked_rest(client, base_url, &address).await?;
+                    let liquid_amount = query_liquid_rest(client, base_url, &address).await?;
+                    let unbonding_amount = query_unbonding_rest(client, base_url, &address).await?;
+                    let rewards_amount = query_rewards_rest(client, base_url, &address).await?;
+                    (sequence, staked_amount, liquid_amount, unbonding_amount, rewards_amount)
+                }
+            };
 
             let output = Output {
                 address,
                 sequence,
                 airdrop_amount: user.amount,
                 staked_amount,
+                liquid_amount,
+                unbonding_amount,
+                rewards_amount,
             };
 
             let output_str = serde_json::to_string(&output)?;
             println!("{output_str}");
 
-            // can i do this type annotation in a better way?
-            Result::<_, Error>::Ok(output)
+            write_output(&writer, format, &output).await?;
+
+            Result::<_, Error>::Ok(())
         }
-    }))
+    })
     .await?;
 
-    let output_str = serde_json::to_string_pretty(&output)?;
-    fs::write(&output_path, output_str)?;
+    writer.lock().await.flush()?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dec_coin_floor_grpc_truncates_sub_unit_rewards() {
+        // 0.5 umars, as marshaled by a raw gRPC `LegacyDec` (scaled by 10^18, no decimal point)
+        assert_eq!(dec_coin_floor_grpc("500000000000000000").unwrap(), 0);
+    }
+}